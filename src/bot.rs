@@ -0,0 +1,507 @@
+use std::iter::repeat;
+
+use rand::seq::SliceRandom as _;
+use shengji::serving_types::UserMessage;
+use shengji_core::{game_state::GameState, interactive::Action, player::Player};
+use shengji_mechanics::{
+    ordered_card::OrderedCard,
+    trick::UnitLike,
+    types::{Card, EffectiveSuit},
+};
+use tracing::{debug, info, trace};
+
+use crate::{
+    error::ShengjiError,
+    strategy::{self, Aggressiveness, BidCandidate},
+    transport::GameTransport,
+};
+
+/// Finds the bot's own player in whatever phase the game is currently in.
+///
+/// `PropagatedState` (accessible from every phase after `Initialize` via
+/// `propagated()`) carries the player list forward for the lifetime of the
+/// game, so this works whether we're resuming after a reconnect or just
+/// joined.
+pub(crate) fn find_me(game_state: &GameState, name: &str) -> Result<Player, ShengjiError> {
+    let players = match game_state {
+        GameState::Initialize(i) => i.players().to_vec(),
+        GameState::Draw(p) => p.propagated().players().to_vec(),
+        GameState::Exchange(p) => p.propagated().players().to_vec(),
+        GameState::Play(p) => p.propagated().players().to_vec(),
+    };
+    players
+        .into_iter()
+        .find(|p| p.name == name)
+        .ok_or_else(|| ShengjiError::PlayerNotFound(name.to_owned()))
+}
+
+/// Which phase a `GameState` represents, independent of what data it
+/// carries. Used only to notice phase *transitions* so we know when to
+/// reset per-phase bookkeeping (e.g. "have we bid yet") without assuming
+/// anything about what phase preceded it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Initialize,
+    Draw,
+    Exchange,
+    Play,
+}
+
+impl From<&GameState> for Phase {
+    fn from(state: &GameState) -> Self {
+        match state {
+            GameState::Initialize(_) => Phase::Initialize,
+            GameState::Draw(_) => Phase::Draw,
+            GameState::Exchange(_) => Phase::Exchange,
+            GameState::Play(_) => Phase::Play,
+        }
+    }
+}
+
+/// Bookkeeping the bot needs to carry between otherwise-independent state
+/// updates: what we've already sent (so we don't repeat ourselves) and
+/// which phase we last saw (so we can detect a fresh entry into a phase).
+///
+/// Everything else about how the bot reacts is decided fresh from each
+/// incoming `GameState` alone — there's no assumption that updates arrive
+/// in any particular order. That's what makes `dispatch` safe to call with
+/// a state from any phase at any time, including a server jumping back to
+/// `Initialize` or the bot joining mid-game after a reconnect.
+struct Bot {
+    name: String,
+    aggressiveness: Aggressiveness,
+    last_phase: Option<Phase>,
+    last_bid_sent: Option<BidCandidate>,
+    buried_kitty: bool,
+}
+
+impl Bot {
+    fn new(name: &str, aggressiveness: Aggressiveness) -> Self {
+        Bot {
+            name: name.to_owned(),
+            aggressiveness,
+            last_phase: None,
+            last_bid_sent: None,
+            buried_kitty: false,
+        }
+    }
+
+    /// Reacts to a single incoming `GameState`, dispatching purely on its
+    /// phase.
+    fn dispatch(
+        &mut self,
+        transport: &mut impl GameTransport,
+        game_state: &GameState,
+    ) -> Result<(), ShengjiError> {
+        let phase = Phase::from(game_state);
+        let entering = self.last_phase != Some(phase);
+        if entering {
+            info!(?phase, ?game_state, "Entering phase");
+            match phase {
+                // A fresh Draw phase means a new hand: forget any bid we'd
+                // sent for the last one.
+                Phase::Draw => self.last_bid_sent = None,
+                // A fresh Exchange phase means we haven't buried the kitty
+                // for this hand yet.
+                Phase::Exchange => self.buried_kitty = false,
+                _ => {}
+            }
+        }
+
+        match game_state {
+            GameState::Initialize(_) => self.handle_initialize(transport, entering)?,
+            GameState::Draw(p) => {
+                let me = find_me(game_state, &self.name)?;
+                self.handle_draw(transport, p, &me)?
+            }
+            GameState::Exchange(p) => {
+                let me = find_me(game_state, &self.name)?;
+                self.handle_exchange(transport, p, &me)?
+            }
+            GameState::Play(p) => {
+                let me = find_me(game_state, &self.name)?;
+                self.handle_play(transport, p, &me)?
+            }
+        }
+
+        self.last_phase = Some(phase);
+        Ok(())
+    }
+
+    /// Marks us as ready the first time we see a fresh Initialize phase.
+    /// Settings changes produce further Initialize updates; we only need
+    /// to send `Ready` once per entry into the phase.
+    fn handle_initialize(
+        &mut self,
+        transport: &mut impl GameTransport,
+        entering: bool,
+    ) -> Result<(), ShengjiError> {
+        if entering {
+            transport.chat(
+                "Beep boop, I'm a bot! I just joined, so please give me a moment to get my bearings.",
+            )?;
+            transport.send(UserMessage::Ready)?;
+        } else {
+            debug!("Waiting for Draw phase");
+        }
+        Ok(())
+    }
+
+    /// Draws a card when it's our turn, and bids when our hand looks
+    /// strong enough to be worth it.
+    fn handle_draw(
+        &mut self,
+        transport: &mut impl GameTransport,
+        p: &shengji_core::game_state::DrawPhase,
+        me: &Player,
+    ) -> Result<(), ShengjiError> {
+        match p.next_player() {
+            Ok(next_player) => {
+                trace!(?next_player, "Next player to draw");
+                if next_player == me.id {
+                    debug!("Drawing card");
+                    transport.send(UserMessage::Action(Action::DrawCard))?;
+                } else {
+                    debug!(?next_player, "Waiting for next player to draw");
+                }
+            }
+            Err(e) => {
+                if e.to_string() == "nobody has bid yet" {
+                    debug!("Waiting for bids to be made")
+                } else {
+                    return Err(ShengjiError::Other(format!(
+                        "unexpected error during Draw phase: {}",
+                        e
+                    )));
+                }
+            }
+        }
+
+        let current_best = p
+            .bids()
+            .iter()
+            .max_by_key(|bid| bid.count)
+            .map(|bid| BidCandidate {
+                card: bid.card,
+                count: bid.count,
+            });
+        let hands = p.hands();
+        let current_hand_counts = hands.get(me.id).unwrap();
+        if let Some(bid) = strategy::choose_bid(
+            current_hand_counts.iter(),
+            p.propagated().rank(),
+            current_best,
+            self.last_bid_sent,
+            self.aggressiveness,
+        ) {
+            debug!(?bid, "Bidding");
+            transport.send(UserMessage::Action(Action::Bid(bid.card, bid.count)))?;
+            self.last_bid_sent = Some(bid);
+        }
+
+        Ok(())
+    }
+
+    /// If we won the bid, picks up the kitty and buries the same number of
+    /// cards back into it. Otherwise there's nothing to do.
+    ///
+    /// The trump used to pick which cards to bury comes from
+    /// `p.propagated().trump()`, not from any bid we remember placing
+    /// ourselves (`Bot` keeps only `last_bid_sent`, and a fresh `Bot` is
+    /// built on every call to `bot::play`, so it's `None` after a
+    /// reconnect). `propagated().trump()` is part of the actual game state,
+    /// so it's always there once the bid phase has ended, regardless of who
+    /// placed the winning bid or when we joined.
+    fn handle_exchange(
+        &mut self,
+        transport: &mut impl GameTransport,
+        p: &shengji_core::game_state::ExchangePhase,
+        me: &Player,
+    ) -> Result<(), ShengjiError> {
+        if self.buried_kitty || p.propagated().landlord() != Some(me.id) {
+            debug!("Waiting for Play phase");
+            return Ok(());
+        }
+
+        let trump = p.propagated().trump();
+        let kitty_size = p.kitty().len();
+        let hands = p.hands();
+        let current_hand_counts = hands.get(me.id).unwrap();
+        let cards_to_bury = strategy::choose_cards_to_bury(
+            current_hand_counts.iter(),
+            |card| trump.effective_suit(*card) == EffectiveSuit::Trump,
+            kitty_size,
+        );
+        debug!(?cards_to_bury, "Burying kitty");
+        transport.send(UserMessage::Action(Action::MoveCardsToKitty(
+            cards_to_bury,
+        )))?;
+        self.buried_kitty = true;
+
+        Ok(())
+    }
+
+    /// Plays a card (or a matching set of cards) when it's our turn.
+    fn handle_play(
+        &mut self,
+        transport: &mut impl GameTransport,
+        p: &shengji_core::game_state::PlayPhase,
+        me: &Player,
+    ) -> Result<(), ShengjiError> {
+        let trick = p.trick();
+        debug!(?trick, "Current trick");
+
+        let played_cards = trick.played_cards();
+        debug!(?played_cards, "Currently played cards");
+
+        match trick.next_player() {
+            Some(next_player_id) => {
+                if next_player_id != me.id {
+                    debug!(?next_player_id, "Waiting for next player to play");
+                    return Ok(());
+                }
+
+                debug!("Playing trick");
+                let settings = p.propagated();
+                let hands = p.hands();
+                let current_hand_counts = hands.get(me.id).unwrap();
+                debug!(?current_hand_counts, "Current hand");
+                let current_hand = current_hand_counts
+                    .iter()
+                    .flat_map(|(card, count)| repeat(*card).take(*count))
+                    .collect::<Vec<_>>();
+
+                match trick.trick_format() {
+                    None => {
+                        assert!(played_cards.len() == 0);
+                        debug!("Starting a new trick");
+
+                        // For now, just play a random card. Any one-card
+                        // starting play will always be valid.
+                        let card = current_hand.choose(&mut rand::thread_rng()).unwrap();
+                        debug!(?card, "Playing card");
+                        transport.send(UserMessage::Action(Action::PlayCards(vec![*card])))?;
+                    }
+                    Some(trick_format) => {
+                        assert!(played_cards.len() > 0);
+                        debug!(?trick_format, "Following this trick format");
+
+                        let cards_in_trick_suit = current_hand
+                            .iter()
+                            .filter(|c| {
+                                trick_format.trump().effective_suit(**c) == trick_format.suit()
+                            })
+                            .copied()
+                            .collect::<Vec<_>>();
+
+                        let matching_play = trick_format
+                            .decomposition(settings.trick_draw_policy())
+                            .filter_map(|format| {
+                                let mut playable = UnitLike::check_play(
+                                    OrderedCard::make_map(
+                                        current_hand.iter().copied(),
+                                        trick_format.trump(),
+                                    ),
+                                    format.iter().cloned(),
+                                    settings.trick_draw_policy(),
+                                );
+
+                                playable.next().map(|units| {
+                                    units
+                                        .iter()
+                                        .flat_map(|unit| {
+                                            unit.iter().flat_map(|(card, count)| {
+                                                repeat(card.card).take(*count)
+                                            })
+                                        })
+                                        .collect::<Vec<_>>()
+                                })
+                            })
+                            .next();
+
+                        let other_cards =
+                            Card::cards(current_hand_counts.iter().filter(|(c, _)| {
+                                trick_format.trump().effective_suit(**c) != trick_format.suit()
+                            }))
+                            .copied()
+                            .collect::<Vec<_>>();
+
+                        let play = select_cards_to_play(
+                            trick_format.size(),
+                            cards_in_trick_suit,
+                            matching_play,
+                            other_cards,
+                        );
+
+                        debug!(?play, "Playing cards");
+                        transport.send(UserMessage::Action(Action::PlayCards(play)))?;
+                    }
+                }
+            }
+            None => {
+                // This happens when the trick has ended (i.e. been won by
+                // somebody), but nobody has moved on to the next trick yet
+                // (i.e. hit the "Finish Trick" button, which sends the
+                // "EndTrick" action).
+                debug!("Waiting for next trick");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Drives a single game over `transport`, for as long as the transport
+/// keeps producing state updates.
+///
+/// Unlike the old phase-by-phase loop this replaced, this never assumes
+/// what phase comes next: every update is dispatched purely on its own
+/// `GameState` variant. That makes it robust to the server jumping back to
+/// `Initialize` mid-session, or to us joining (or rejoining, after a
+/// reconnect) a game that's already past the phase we'd otherwise expect to
+/// start in.
+pub fn play(
+    transport: &mut impl GameTransport,
+    name: &str,
+    aggressiveness: Aggressiveness,
+) -> Result<(), ShengjiError> {
+    let mut bot = Bot::new(name, aggressiveness);
+    loop {
+        let game_state = transport.read_state()?;
+        bot.dispatch(transport, &game_state)?;
+    }
+}
+
+/// Given the required play size, the cards already known to follow the
+/// trick's suit (i.e. legally playable as-is), some legal decomposition of
+/// the trick format already matched against the current hand (if any), and
+/// the rest of the hand, picks which cards to actually play.
+///
+/// This is the part of the trick-following logic that doesn't need to know
+/// anything about `GameState`, `TrickFormat`, or `UnitLike` — just plain
+/// collections of cards — so it can be unit tested on its own instead of
+/// only through a live connection.
+fn select_cards_to_play<T: Clone + PartialEq>(
+    num_required: usize,
+    cards_in_trick_suit: Vec<T>,
+    matching_play: Option<Vec<T>>,
+    mut other_cards: Vec<T>,
+) -> Vec<T> {
+    let mut play = match matching_play {
+        Some(matching) if matching.len() == num_required => matching,
+        Some(_) if num_required >= cards_in_trick_suit.len() => cards_in_trick_suit,
+        Some(mut matching) => {
+            // There are more available cards than required; we must at least
+            let mut available_cards = cards_in_trick_suit;
+            // pick the matching. Do this inefficiently!
+            for m in &matching {
+                available_cards.remove(available_cards.iter().position(|c| *c == *m).unwrap());
+            }
+            available_cards.shuffle(&mut rand::thread_rng());
+            matching.extend(
+                available_cards[0..num_required - matching.len()]
+                    .iter()
+                    .cloned(),
+            );
+
+            matching
+        }
+        None => cards_in_trick_suit,
+    };
+    let required_other_cards = num_required - play.len();
+    if required_other_cards > 0 {
+        other_cards.shuffle(&mut rand::thread_rng());
+        play.extend(other_cards[0..required_other_cards].iter().cloned());
+    }
+    play
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockTransport;
+
+    #[test]
+    fn select_cards_to_play_uses_the_exact_match_when_it_satisfies_the_trick() {
+        let play = select_cards_to_play(2, vec![1, 2, 3], Some(vec![1, 2]), vec![9, 10]);
+        assert_eq!(play, vec![1, 2]);
+    }
+
+    #[test]
+    fn select_cards_to_play_pads_a_partial_match_from_the_trick_suit() {
+        let play = select_cards_to_play(2, vec![1, 2, 3], Some(vec![1]), vec![9, 10]);
+        assert_eq!(play.len(), 2);
+        assert!(play.contains(&1));
+    }
+
+    #[test]
+    fn select_cards_to_play_falls_back_to_other_cards_when_trick_suit_is_short() {
+        let play = select_cards_to_play(2, vec![1], None, vec![9, 10]);
+        assert_eq!(play.len(), 2);
+        assert!(play.contains(&1));
+        assert!(play.contains(&9) || play.contains(&10));
+    }
+
+    #[test]
+    fn mock_transport_records_every_outgoing_message() {
+        let mut transport = MockTransport::new(Vec::new());
+        transport.send(UserMessage::Ready).unwrap();
+        transport.chat("hello").unwrap();
+        assert_eq!(transport.sent.len(), 2);
+        match &transport.sent[1] {
+            UserMessage::Message(msg) => assert_eq!(msg, "hello"),
+            other => panic!("expected a chat message, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn dispatch_plays_a_legal_follow_in_play_phase() {
+        use shengji_mechanics::types::Suit;
+
+        // `GameState` is deserialized straight off the wire in
+        // `ShengjiSocket::read_message`, so a canned fixture built the same
+        // way is the natural way to drive `dispatch` through a real Play
+        // phase without a live server: opponent (id 1) led a Hearts card
+        // (trump is Spades), and we (id 0) hold exactly one card of that
+        // suit, so the only legal follow is to play it.
+        let fixture = serde_json::json!({
+            "Play": {
+                "propagated": {
+                    "players": [
+                        {"id": 0, "name": "autoshengji"},
+                        {"id": 1, "name": "opponent"},
+                    ],
+                    "landlord": 1,
+                    "hide_landlord_points": false,
+                    "trick_draw_policy": "NoProtections",
+                    "trump": {"Standard": {"suit": "Spades", "number": "Two"}},
+                },
+                "hands": {
+                    "0": [[{"Suited": {"suit": "Hearts", "rank": "Four"}}, 1]],
+                    "1": [],
+                },
+                "kitty": [],
+                "trick": {
+                    "player_order": [1, 0],
+                    "played_cards": [
+                        {"id": 1, "cards": [{"Suited": {"suit": "Hearts", "rank": "Seven"}}]},
+                    ],
+                },
+            },
+        });
+        let game_state: GameState =
+            serde_json::from_value(fixture).expect("fixture should match GameState's schema");
+
+        let mut transport = MockTransport::new(Vec::new());
+        let mut bot = Bot::new("autoshengji", Aggressiveness::default());
+        bot.dispatch(&mut transport, &game_state).unwrap();
+
+        match transport.sent.last() {
+            Some(UserMessage::Action(Action::PlayCards(cards))) => {
+                assert_eq!(cards.len(), 1);
+                assert!(matches!(cards[0], Card::Suited { suit: Suit::Hearts, .. }));
+            }
+            other => panic!("expected a legal follow, got {:?}", other),
+        }
+    }
+}
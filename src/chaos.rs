@@ -0,0 +1,138 @@
+use std::{thread, time::Duration};
+
+use clap::Args;
+use rand::Rng as _;
+use shengji::serving_types::UserMessage;
+use shengji_types::GameMessage;
+use tracing::{debug, warn};
+
+use crate::{error::ShengjiError, transport::GameTransport};
+
+/// Configuration for `ChaosTransport`'s simulated bad network, settable from
+/// the command line (e.g. `--chaos-loss 0.3 --chaos-delay-ms 500`).
+#[derive(Debug, Clone, Args)]
+pub struct ChaosConfig {
+    /// Probability (0.0-1.0) that any given incoming message is dropped.
+    #[arg(long = "chaos-loss", default_value_t = 0.0)]
+    pub loss: f64,
+
+    /// Probability (0.0-1.0) that any given incoming message is also
+    /// redelivered a second time.
+    #[arg(long = "chaos-duplicate", default_value_t = 0.0)]
+    pub duplicate: f64,
+
+    /// Probability (0.0-1.0) that any given incoming message fails to
+    /// decode, as if its payload had been truncated or corrupted in
+    /// transit. `ChaosTransport` sits above the decompression boundary (so
+    /// it can also wrap non-socket transports like `MockTransport`, which
+    /// have no wire bytes to corrupt in the first place), so this simulates
+    /// the resulting decode error directly rather than mutating any bytes.
+    #[arg(long = "chaos-decode-failure", default_value_t = 0.0)]
+    pub decode_failure: f64,
+
+    /// Fixed added latency, in milliseconds, applied to every read.
+    #[arg(long = "chaos-delay-ms", default_value_t = 0)]
+    pub delay_ms: u64,
+
+    /// Standard deviation, in milliseconds, of normally-distributed jitter
+    /// added on top of `delay_ms`.
+    #[arg(long = "chaos-jitter-ms", default_value_t = 0)]
+    pub jitter_ms: u64,
+}
+
+impl ChaosConfig {
+    /// Whether any chaos is actually configured. When this is false,
+    /// `ChaosTransport` should just be skipped entirely.
+    pub fn is_enabled(&self) -> bool {
+        self.loss > 0.0
+            || self.duplicate > 0.0
+            || self.decode_failure > 0.0
+            || self.delay_ms > 0
+            || self.jitter_ms > 0
+    }
+
+    fn delay(&self) -> Duration {
+        let jitter_ms = if self.jitter_ms > 0 {
+            // Box-Muller transform: turn two uniform samples into one
+            // standard-normal sample, then scale by the configured stddev.
+            let mut rng = rand::thread_rng();
+            let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+            let u2: f64 = rng.gen_range(0.0..1.0);
+            let standard_normal =
+                (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+            (standard_normal * self.jitter_ms as f64).max(0.0)
+        } else {
+            0.0
+        };
+        Duration::from_millis(self.delay_ms) + Duration::from_millis(jitter_ms as u64)
+    }
+}
+
+/// Decorates a `GameTransport` with a simulated bad network: added latency
+/// (fixed plus jitter), dropped messages, duplicated messages, and messages
+/// that fail to decode.
+///
+/// `GameTransport` operates above the zstd decompression boundary, so this
+/// can't actually truncate or bit-flip a wire frame — it can only produce
+/// the same `ShengjiError::Decompress` a corrupted payload would eventually
+/// surface as. That's still enough to reproduce the hangs and crashes a
+/// flaky connection causes in-process, without any external
+/// traffic-control tooling, by driving the reconnection logic against this
+/// instead of a live socket.
+pub struct ChaosTransport<T> {
+    inner: T,
+    config: ChaosConfig,
+    pending_duplicate: Option<GameMessage>,
+}
+
+impl<T: GameTransport> ChaosTransport<T> {
+    pub fn new(inner: T, config: ChaosConfig) -> Self {
+        ChaosTransport {
+            inner,
+            config,
+            pending_duplicate: None,
+        }
+    }
+}
+
+impl<T: GameTransport> GameTransport for ChaosTransport<T> {
+    fn read_message(&mut self) -> Result<GameMessage, ShengjiError> {
+        if let Some(msg) = self.pending_duplicate.take() {
+            debug!("Chaos: redelivering duplicated message");
+            return Ok(msg);
+        }
+
+        loop {
+            let delay = self.config.delay();
+            if !delay.is_zero() {
+                thread::sleep(delay);
+            }
+
+            let msg = self.inner.read_message()?;
+
+            if rand::thread_rng().gen_bool(self.config.loss.clamp(0.0, 1.0)) {
+                warn!("Chaos: dropping message");
+                continue;
+            }
+
+            if rand::thread_rng().gen_bool(self.config.decode_failure.clamp(0.0, 1.0)) {
+                warn!("Chaos: simulating a decode failure");
+                return Err(ShengjiError::Decompress(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "chaos: simulated truncated/corrupted zstd payload",
+                )));
+            }
+
+            if rand::thread_rng().gen_bool(self.config.duplicate.clamp(0.0, 1.0)) {
+                debug!("Chaos: duplicating message");
+                self.pending_duplicate = Some(msg.clone());
+            }
+
+            return Ok(msg);
+        }
+    }
+
+    fn send(&mut self, msg: UserMessage) -> Result<(), ShengjiError> {
+        self.inner.send(msg)
+    }
+}
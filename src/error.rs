@@ -0,0 +1,58 @@
+use thiserror::Error;
+use tungstenite::Message;
+
+/// Errors encountered while talking to the game server.
+///
+/// Variants are split into transient failures (a dropped socket, a garbled
+/// frame, the server rejecting one of our actions) that are worth retrying,
+/// and fatal failures (the bot isn't in the game state it expects) that will
+/// just happen again on reconnect, so we give up instead of looping forever.
+#[derive(Debug, Error)]
+pub enum ShengjiError {
+    #[error("failed to connect to the game server: {0}")]
+    Connect(#[source] tungstenite::Error),
+
+    #[error("websocket I/O error: {0}")]
+    Io(#[from] tungstenite::Error),
+
+    #[error("failed to decompress message: {0}")]
+    Decompress(#[source] std::io::Error),
+
+    #[error("failed to deserialize message: {0}")]
+    Deserialize(#[from] serde_json::Error),
+
+    #[error("received unexpected message from server: {0:?}")]
+    UnexpectedMessage(Message),
+
+    /// Sent any time the server rejects something. `transient` distinguishes
+    /// the two cases this covers: an in-game action rejection (e.g. an
+    /// illegal bid or an out-of-turn play), which is a cheap, usually
+    /// one-off strategy mistake that reconnecting (and resyncing to
+    /// whatever state the room is actually in) recovers from; versus a
+    /// join-time/room-level rejection (e.g. the room doesn't exist), which
+    /// will just recur forever on reconnect and so is fatal.
+    #[error("server reported an error: {message}")]
+    Server { message: String, transient: bool },
+
+    #[error("couldn't find a player named {0:?} in the game state")]
+    PlayerNotFound(String),
+
+    #[error("{0}")]
+    Other(String),
+}
+
+impl ShengjiError {
+    /// Whether this error is worth retrying (a flaky connection) as opposed
+    /// to one that will just recur (e.g. the room doesn't exist).
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ShengjiError::Connect(_)
+            | ShengjiError::Io(_)
+            | ShengjiError::Decompress(_)
+            | ShengjiError::Deserialize(_)
+            | ShengjiError::UnexpectedMessage(_) => true,
+            ShengjiError::Server { transient, .. } => *transient,
+            ShengjiError::PlayerNotFound(_) | ShengjiError::Other(_) => false,
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use std::collections::VecDeque;
+
+use shengji::serving_types::UserMessage;
+use shengji_types::GameMessage;
+
+use crate::{error::ShengjiError, transport::GameTransport};
+
+/// A `GameTransport` that replays a scripted sequence of incoming messages
+/// and records every outgoing one, for use in tests.
+///
+/// Reading past the end of the script is a test setup bug, not something
+/// the bot should ever see in practice, so it panics rather than returning
+/// an error.
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    incoming: VecDeque<GameMessage>,
+    pub sent: Vec<UserMessage>,
+}
+
+impl MockTransport {
+    pub fn new(incoming: impl Into<VecDeque<GameMessage>>) -> Self {
+        MockTransport {
+            incoming: incoming.into(),
+            sent: Vec::new(),
+        }
+    }
+}
+
+impl GameTransport for MockTransport {
+    fn read_message(&mut self) -> Result<GameMessage, ShengjiError> {
+        Ok(self
+            .incoming
+            .pop_front()
+            .expect("MockTransport script exhausted"))
+    }
+
+    fn send(&mut self, msg: UserMessage) -> Result<(), ShengjiError> {
+        self.sent.push(msg);
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write as _},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use shengji::serving_types::UserMessage;
+use shengji_core::{game_state::GameState, player::PlayerID};
+use shengji_types::GameMessage;
+use tracing::warn;
+
+use crate::{bot::find_me, error::ShengjiError, transport::GameTransport};
+
+/// One line of a recorded game: either a message we received from the
+/// server or one we sent to it, with enough context to replay it later.
+///
+/// `me` is filled in once the bot's own player id can be resolved from a
+/// `State` message (i.e. from the first one onward); it's `None` for
+/// anything recorded before that.
+#[derive(Debug, Serialize)]
+#[serde(tag = "direction")]
+enum RecordedEvent<'a> {
+    Received {
+        at_millis: u128,
+        me: Option<PlayerID>,
+        message: &'a GameMessage,
+    },
+    Sent {
+        at_millis: u128,
+        me: Option<PlayerID>,
+        message: &'a UserMessage,
+    },
+}
+
+/// The writable half of a recording: the open file and the resolved-so-far
+/// `me` player id, independent of whatever transport is currently feeding it
+/// messages.
+///
+/// Kept separate from `RecordingTransport` so a reconnect (which replaces
+/// the transport underneath) can hand the same sink to the new
+/// `RecordingTransport`, keeping one game's recording in a single file no
+/// matter how many times `play_game` has to reconnect to finish it.
+///
+/// Files are named after the room and the sink's open time, so recordings
+/// of the same room on different days don't clobber each other.
+pub struct RecordingSink {
+    writer: BufWriter<File>,
+    name: String,
+    me: Option<PlayerID>,
+}
+
+impl RecordingSink {
+    pub fn open(dir: &Path, room_name: &str, name: &str) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let file_name = format!("{}-{}.jsonl", sanitize_for_filename(room_name), now_millis());
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(file_name))?;
+        Ok(RecordingSink {
+            writer: BufWriter::new(file),
+            name: name.to_owned(),
+            me: None,
+        })
+    }
+
+    fn record(&mut self, event: RecordedEvent) -> Result<(), ShengjiError> {
+        let line = serde_json::to_string(&event)?;
+        writeln!(self.writer, "{}", line).map_err(|e| ShengjiError::Other(e.to_string()))?;
+        self.writer
+            .flush()
+            .map_err(|e| ShengjiError::Other(e.to_string()))
+    }
+
+    /// Opportunistically resolves our own player id from a `State` message,
+    /// so later events can be tagged with it. Failing to resolve it (e.g.
+    /// we haven't been added to the player list yet) is not an error here;
+    /// we just keep recording with `me: None` until it succeeds.
+    fn note_state(&mut self, state: &GameState) {
+        match find_me(state, &self.name) {
+            Ok(me) => self.me = Some(me.id),
+            Err(e) => warn!(error = %e, "Couldn't resolve our player id for recording yet"),
+        }
+    }
+}
+
+/// Decorates a `GameTransport`, appending every message that crosses it —
+/// in either direction — to a `RecordingSink`. Used to build up a
+/// replayable corpus of full games: the `message` of each `Received` line is
+/// a `GameMessage` that can be fed into `MockTransport` for regression
+/// testing, and the corpus can eventually be used as training
+/// data for a smarter bidding/play policy.
+pub struct RecordingTransport<T> {
+    inner: T,
+    sink: RecordingSink,
+}
+
+impl<T: GameTransport> RecordingTransport<T> {
+    pub fn new(inner: T, sink: RecordingSink) -> Self {
+        RecordingTransport { inner, sink }
+    }
+
+    /// Reclaims the sink, so it can be handed to a fresh `RecordingTransport`
+    /// wrapping whatever transport a reconnect produces.
+    pub fn into_sink(self) -> RecordingSink {
+        self.sink
+    }
+}
+
+impl<T: GameTransport> GameTransport for RecordingTransport<T> {
+    fn read_message(&mut self) -> Result<GameMessage, ShengjiError> {
+        let message = self.inner.read_message()?;
+        if let GameMessage::State { state } = &message {
+            self.sink.note_state(state);
+        }
+        self.sink.record(RecordedEvent::Received {
+            at_millis: now_millis(),
+            me: self.sink.me,
+            message: &message,
+        })?;
+        Ok(message)
+    }
+
+    fn send(&mut self, msg: UserMessage) -> Result<(), ShengjiError> {
+        self.sink.record(RecordedEvent::Sent {
+            at_millis: now_millis(),
+            me: self.sink.me,
+            message: &msg,
+        })?;
+        self.inner.send(msg)
+    }
+}
+
+fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+fn sanitize_for_filename(room_name: &str) -> String {
+    room_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,221 @@
+use std::iter::repeat;
+
+use shengji_mechanics::types::{Card, Rank};
+
+/// How eager the bot is to escalate over existing bids, as a value in
+/// `[0.0, 1.0]`. In a standard 4-player game (2 decks), the most copies of
+/// any single level-rank card or joker you can ever hold is a pair, so the
+/// scale tops out there: above `0.66`, a single card is enough to bid; at or
+/// below that, the bot holds out for a pair.
+#[derive(Debug, Clone, Copy)]
+pub struct Aggressiveness(pub f64);
+
+impl Default for Aggressiveness {
+    fn default() -> Self {
+        Aggressiveness(0.3)
+    }
+}
+
+impl Aggressiveness {
+    fn min_bid_count(&self) -> usize {
+        if self.0 > 0.66 {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+/// A candidate (or already-placed) bid: some number of copies of a specific
+/// card, establishing that card's suit as the trump candidate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BidCandidate {
+    pub card: Card,
+    pub count: usize,
+}
+
+/// Whether `card` is legal to bid with at the given level: a card of the
+/// level rank, or either joker. The server only accepts bids made up of
+/// these, so a candidate of any other rank would just be rejected.
+fn is_biddable(card: Card, level: Rank) -> bool {
+    match card {
+        Card::Suited { rank, .. } => rank == level,
+        Card::SmallJoker | Card::BigJoker => true,
+    }
+}
+
+/// Looks at the bot's hand and decides whether to send a new bid.
+///
+/// We only consider cards of the current level rank (plus jokers), since
+/// those are the only cards the server will accept a bid with. Among
+/// those, we look for the one we hold the most copies of, since multiple
+/// copies of the same card is the strongest signal of a good trump suit.
+/// We only bid if that beats both our own last bid and the current high
+/// bid — the server rejects bids that don't raise the stakes, so there's
+/// no point sending one that can't.
+pub fn choose_bid<'a>(
+    hand: impl Iterator<Item = (&'a Card, &'a usize)>,
+    level: Rank,
+    current_best: Option<BidCandidate>,
+    already_sent: Option<BidCandidate>,
+    aggressiveness: Aggressiveness,
+) -> Option<BidCandidate> {
+    let min_count = aggressiveness.min_bid_count();
+
+    let best_in_hand = hand
+        .filter(|(card, count)| **count >= min_count && is_biddable(**card, level))
+        .max_by_key(|(_, count)| **count)
+        .map(|(card, count)| BidCandidate {
+            card: *card,
+            count: *count,
+        })?;
+
+    if already_sent.map_or(false, |sent| best_in_hand.count <= sent.count) {
+        return None;
+    }
+
+    let beats_current_best = match current_best {
+        Some(current) => best_in_hand.count > current.count,
+        None => true,
+    };
+
+    beats_current_best.then_some(best_in_hand)
+}
+
+fn is_point_card(rank: Rank) -> bool {
+    matches!(rank, Rank::Five | Rank::Ten | Rank::King)
+}
+
+/// Chooses which cards to bury in the kitty after winning the bid.
+///
+/// Prefers low, non-trump, non-point cards, since those are the least
+/// useful to keep and the least costly to hand to the other team if they
+/// win the last trick.
+///
+/// `is_trump` decides whether a given card counts as trump. The caller
+/// (rather than this function) owns that decision so it can be driven by
+/// the game's actual declared trump (e.g. `Trump::effective_suit`), which
+/// covers off-suit trump-rank cards and jokers that a plain `Suit`
+/// comparison would miss.
+pub fn choose_cards_to_bury<'a>(
+    hand: impl Iterator<Item = (&'a Card, &'a usize)>,
+    is_trump: impl Fn(&Card) -> bool,
+    kitty_size: usize,
+) -> Vec<Card> {
+    let mut cards = hand
+        .flat_map(|(card, count)| repeat(*card).take(*count))
+        .collect::<Vec<_>>();
+
+    cards.sort_by_key(|card| match card {
+        Card::Suited { rank, .. } => (is_trump(card), is_point_card(*rank), *rank),
+        _ => (true, true, Rank::Ace),
+    });
+
+    cards.into_iter().take(kitty_size).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shengji_mechanics::types::Suit;
+
+    fn card(suit: Suit, rank: Rank) -> Card {
+        Card::Suited { suit, rank }
+    }
+
+    #[test]
+    fn choose_bid_picks_the_deepest_run_in_hand() {
+        let hand = vec![
+            (card(Suit::Hearts, Rank::Three), 2usize),
+            (card(Suit::Spades, Rank::Three), 3usize),
+        ];
+        let hand_refs = hand.iter().map(|(c, n)| (c, n));
+        let bid = choose_bid(hand_refs, Rank::Three, None, None, Aggressiveness(0.5)).unwrap();
+        assert_eq!(bid.card, card(Suit::Spades, Rank::Three));
+        assert_eq!(bid.count, 3);
+    }
+
+    #[test]
+    fn choose_bid_ignores_off_level_cards_even_with_a_longer_run() {
+        let hand = vec![
+            (card(Suit::Hearts, Rank::Two), 4usize),
+            (card(Suit::Spades, Rank::Three), 2usize),
+        ];
+        let hand_refs = hand.iter().map(|(c, n)| (c, n));
+        let bid = choose_bid(hand_refs, Rank::Three, None, None, Aggressiveness(0.5)).unwrap();
+        assert_eq!(bid.card, card(Suit::Spades, Rank::Three));
+    }
+
+    #[test]
+    fn choose_bid_allows_jokers_regardless_of_level() {
+        let hand = vec![(Card::SmallJoker, 2usize)];
+        let hand_refs = hand.iter().map(|(c, n)| (c, n));
+        let bid = choose_bid(hand_refs, Rank::Three, None, None, Aggressiveness(0.5)).unwrap();
+        assert_eq!(bid.card, Card::SmallJoker);
+    }
+
+    #[test]
+    fn choose_bid_does_not_repeat_our_own_bid() {
+        let hand = vec![(card(Suit::Spades, Rank::Three), 3usize)];
+        let hand_refs = hand.iter().map(|(c, n)| (c, n));
+        let already_sent = BidCandidate {
+            card: card(Suit::Spades, Rank::Three),
+            count: 3,
+        };
+        let bid = choose_bid(
+            hand_refs,
+            Rank::Three,
+            None,
+            Some(already_sent),
+            Aggressiveness(0.5),
+        );
+        assert!(bid.is_none());
+    }
+
+    #[test]
+    fn choose_bid_bids_a_pair_at_default_aggressiveness() {
+        let hand = vec![(card(Suit::Spades, Rank::Three), 2usize)];
+        let hand_refs = hand.iter().map(|(c, n)| (c, n));
+        let bid = choose_bid(
+            hand_refs,
+            Rank::Three,
+            None,
+            None,
+            Aggressiveness::default(),
+        )
+        .unwrap();
+        assert_eq!(bid.card, card(Suit::Spades, Rank::Three));
+        assert_eq!(bid.count, 2);
+    }
+
+    #[test]
+    fn choose_bid_will_not_undercut_the_current_best() {
+        let hand = vec![(card(Suit::Spades, Rank::Three), 2usize)];
+        let hand_refs = hand.iter().map(|(c, n)| (c, n));
+        let current_best = BidCandidate {
+            card: card(Suit::Hearts, Rank::Two),
+            count: 3,
+        };
+        let bid = choose_bid(
+            hand_refs,
+            Rank::Three,
+            Some(current_best),
+            None,
+            Aggressiveness(1.0),
+        );
+        assert!(bid.is_none());
+    }
+
+    #[test]
+    fn choose_cards_to_bury_avoids_trump_and_point_cards_when_possible() {
+        let hand = vec![
+            (card(Suit::Spades, Rank::Three), 1usize),
+            (card(Suit::Hearts, Rank::Five), 1usize),
+            (card(Suit::Clubs, Rank::Four), 1usize),
+        ];
+        let hand_refs = hand.iter().map(|(c, n)| (c, n));
+        let is_trump = |c: &Card| matches!(c, Card::Suited { suit, .. } if *suit == Suit::Spades);
+        let buried = choose_cards_to_bury(hand_refs, is_trump, 1);
+        assert_eq!(buried, vec![card(Suit::Clubs, Rank::Four)]);
+    }
+}
@@ -0,0 +1,135 @@
+use std::net::TcpStream;
+
+use shengji::serving_types::{JoinRoom, UserMessage};
+use shengji_core::game_state::GameState;
+use shengji_types::{GameMessage, ZSTD_ZSTD_DICT};
+use tracing::{instrument, trace};
+use tungstenite::{stream::MaybeTlsStream, Message, WebSocket};
+
+use crate::error::ShengjiError;
+
+/// Everything the bot needs from the connection to the game server.
+///
+/// This exists so the phase logic in `bot` can be driven by either a real
+/// `ShengjiSocket` or a scripted `MockTransport` in tests, without knowing
+/// which one it's talking to.
+pub trait GameTransport {
+    /// Blocks until the next message of any kind arrives.
+    fn read_message(&mut self) -> Result<GameMessage, ShengjiError>;
+
+    /// Sends a `UserMessage` to the server.
+    fn send(&mut self, msg: UserMessage) -> Result<(), ShengjiError>;
+
+    /// Blocks until the next `GameMessage::State` arrives, discarding any
+    /// other message types along the way.
+    fn read_state(&mut self) -> Result<GameState, ShengjiError> {
+        loop {
+            if let GameMessage::State { state } = self.read_message()? {
+                return Ok(state);
+            }
+        }
+    }
+
+    /// Sends a chat message to the room.
+    fn chat(&mut self, msg: &str) -> Result<(), ShengjiError> {
+        self.send(UserMessage::Message(msg.to_owned()))
+    }
+}
+
+impl<T: GameTransport + ?Sized> GameTransport for Box<T> {
+    fn read_message(&mut self) -> Result<GameMessage, ShengjiError> {
+        (**self).read_message()
+    }
+
+    fn send(&mut self, msg: UserMessage) -> Result<(), ShengjiError> {
+        (**self).send(msg)
+    }
+}
+
+pub struct ShengjiSocket<'a> {
+    ws: WebSocket<MaybeTlsStream<TcpStream>>,
+    decompressor: zstd::bulk::Decompressor<'a>,
+    /// Whether we've successfully read at least one `State` message on this
+    /// connection. A `GameMessage::Error` that arrives before this is set
+    /// is a join-time/room-level rejection (e.g. the room doesn't exist) and
+    /// will just recur on reconnect, so it's fatal; one that arrives after
+    /// is an in-game action the server rejected (e.g. an illegal bid), which
+    /// reconnecting (and resyncing to wherever the room actually is) can
+    /// cheaply recover from.
+    synced: bool,
+}
+
+impl std::fmt::Debug for ShengjiSocket<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShengjiSocket")
+            .field("ws", &self.ws)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ShengjiSocket<'_> {
+    #[instrument(level = "debug")]
+    pub fn connect(room_name: String, name: String) -> Result<Self, ShengjiError> {
+        let decompressor = zstd::bulk::Decompressor::with_dictionary(
+            &zstd::bulk::decompress(ZSTD_ZSTD_DICT, 112_640).unwrap(),
+        )
+        .unwrap();
+        let (ws, _) = tungstenite::connect("wss://shengji.battery.aeturnalus.com/api")
+            .map_err(ShengjiError::Connect)?;
+
+        let mut socket = ShengjiSocket {
+            ws,
+            decompressor,
+            synced: false,
+        };
+
+        let join_message = serde_json::to_string(&JoinRoom { room_name, name })?;
+        socket.ws.send(Message::Text(join_message))?;
+
+        Ok(socket)
+    }
+}
+
+impl GameTransport for ShengjiSocket<'_> {
+    #[instrument(level = "trace", skip(self))]
+    fn read_message(&mut self) -> Result<GameMessage, ShengjiError> {
+        let message = self.ws.read()?;
+        match message {
+            Message::Binary(data) => {
+                trace!(?data, "Received binary message from server");
+                let decompressed = self
+                    .decompressor
+                    .decompress(&data, data.capacity() * 10)
+                    .map_err(ShengjiError::Decompress)?;
+                trace!(?decompressed, "Decompressed message");
+                let decoded: GameMessage = serde_json::from_slice(&decompressed)?;
+                trace!(?decoded, "Decoded message");
+                if matches!(decoded, GameMessage::State { .. }) {
+                    self.synced = true;
+                }
+                if let GameMessage::Error(err) = &decoded {
+                    let message = format!("{:?}", err);
+                    // Best-effort: if this also fails, we're disconnecting anyway.
+                    let _ = self.chat(&format!(
+                        "Whoops, something went wrong! The error message is {}. Disconnecting.",
+                        message
+                    ));
+                    return Err(ShengjiError::Server {
+                        message,
+                        transient: self.synced,
+                    });
+                }
+                Ok(decoded)
+            }
+            _ => Err(ShengjiError::UnexpectedMessage(message)),
+        }
+    }
+
+    #[instrument(level = "trace", skip(self))]
+    fn send(&mut self, msg: UserMessage) -> Result<(), ShengjiError> {
+        let message = serde_json::to_string(&msg)?;
+        trace!(?message, "Sending message");
+        self.ws.send(Message::Text(message))?;
+        Ok(())
+    }
+}